@@ -0,0 +1,125 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fuzzes `i18n_helpers::translate` against an arbitrary `Catalog` and
+//! arbitrary markdown input, looking for panics and for violations of
+//! the invariants `translate` is supposed to uphold: trailing-newline
+//! behavior is preserved, blank-line-only input round-trips unchanged,
+//! and code block contents are never rewritten.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use i18n_helpers::{translate, TranslationStats};
+use libfuzzer_sys::fuzz_target;
+use polib::catalog::Catalog;
+use polib::message::Message;
+use pulldown_cmark::{Event, Parser, Tag};
+
+#[derive(Arbitrary, Debug)]
+struct FuzzInput {
+    messages: Vec<(String, String)>,
+    markdown: String,
+}
+
+/// Whether `text` is safe to use as a synthesized `msgstr`: it must
+/// not itself open new block structure (a code fence, heading, list,
+/// block quote, table, or rule) when re-parsed standalone. A
+/// translation is only ever substituted for a single inline group, so
+/// one that introduces block structure is not a realistic translation
+/// and would make `code_block_contents` below flag a legitimate
+/// substitution as if `translate` had rewritten a code block itself.
+fn is_inline_only(text: &str) -> bool {
+    Parser::new(text).all(|event| {
+        !matches!(
+            event,
+            Event::Start(Tag::CodeBlock(_))
+                | Event::End(Tag::CodeBlock(_))
+                | Event::Start(Tag::Heading(..))
+                | Event::End(Tag::Heading(..))
+                | Event::Start(Tag::BlockQuote)
+                | Event::End(Tag::BlockQuote)
+                | Event::Start(Tag::List(_))
+                | Event::End(Tag::List(_))
+                | Event::Start(Tag::Table(_))
+                | Event::End(Tag::Table(_))
+                | Event::Rule
+        )
+    })
+}
+
+/// The literal text of every fenced/indented code block in `markdown`,
+/// in order. `translate` never translates a code block's contents
+/// (only the prose around it), so this is exactly what must survive
+/// a round-trip through `translate` unchanged, regardless of what
+/// msgstr the fuzzer's arbitrary catalog supplies for anything else.
+fn code_block_contents(markdown: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    let mut in_code_block = false;
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                current.clear();
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                blocks.push(std::mem::take(&mut current));
+            }
+            Event::Text(text) if in_code_block => current.push_str(&text),
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+fuzz_target!(|input: FuzzInput| {
+    let mut catalog = Catalog::new();
+    for (msgid, msgstr) in &input.messages {
+        if msgid.is_empty() || !is_inline_only(msgstr) {
+            continue;
+        }
+        catalog.messages.push(Message::new_singular(msgid, msgstr));
+    }
+
+    let mut stats = TranslationStats::default();
+    let output = translate(
+        &input.markdown,
+        std::slice::from_ref(&catalog),
+        None,
+        &mut stats,
+    );
+
+    assert_eq!(
+        output.ends_with('\n'),
+        input.markdown.ends_with('\n'),
+        "trailing newline was not preserved"
+    );
+
+    if input.markdown.chars().all(|c| c == '\n') {
+        assert_eq!(
+            output, input.markdown,
+            "blank-line-only input did not round-trip unchanged"
+        );
+    }
+
+    assert_eq!(
+        code_block_contents(&output),
+        code_block_contents(&input.markdown),
+        "a code block's contents were rewritten"
+    );
+});
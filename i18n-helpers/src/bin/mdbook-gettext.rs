@@ -24,44 +24,43 @@
 //! See `TRANSLATIONS.md` in the repository root for more information.
 
 use anyhow::{anyhow, Context};
-use i18n_helpers::extract_paragraphs;
+use i18n_helpers::{translate, TranslationStats};
 use mdbook::book::Book;
+use mdbook::config::Config;
 use mdbook::preprocess::{CmdPreprocessor, PreprocessorContext};
 use mdbook::BookItem;
-use polib::catalog::Catalog;
 use polib::po_file;
 use semver::{Version, VersionReq};
 use std::io;
 use std::path::Path;
 use std::process;
+use toml::Value;
 
-fn translate(text: &str, catalog: &Catalog) -> String {
-    let mut output = String::with_capacity(text.len());
-    let mut current_lineno = 1;
-
-    for (lineno, paragraph) in extract_paragraphs(text) {
-        // Fill in blank lines between paragraphs. This is important
-        // for code blocks where blank lines are significant.
-        while current_lineno < lineno {
-            output.push('\n');
-            current_lineno += 1;
-        }
-        current_lineno += paragraph.lines().count();
-
-        let translated = catalog
-            .find_message(paragraph)
-            .and_then(|msg| msg.get_msgstr().ok())
-            .filter(|msgstr| !msgstr.is_empty())
-            .map(|msgstr| msgstr.as_str())
-            .unwrap_or(paragraph);
-        output.push_str(translated);
+/// Accepts either a single PO file path or an array of paths, in
+/// `preprocessor.gettext.po-file`, to support layering a book-specific
+/// override catalog on top of a shared base translation.
+fn po_file_paths(po_file: &Value) -> anyhow::Result<Vec<&str>> {
+    if let Some(path) = po_file.as_str() {
+        return Ok(vec![path]);
     }
-
-    if text.ends_with('\n') {
-        output.push('\n');
+    if let Some(paths) = po_file.as_array() {
+        return paths
+            .iter()
+            .map(|value| {
+                value.as_str().ok_or_else(|| {
+                    anyhow!(
+                        "Expected a string in preprocessor.gettext.po-file, found {value} ({})",
+                        value.type_str()
+                    )
+                })
+            })
+            .collect();
     }
-
-    output
+    Err(anyhow!(
+        "Expected a string or array of strings for preprocessor.gettext.po-file, \
+         found {po_file} ({})",
+        po_file.type_str()
+    ))
 }
 
 fn translate_book(ctx: &PreprocessorContext, mut book: Book) -> anyhow::Result<Book> {
@@ -72,30 +71,77 @@ fn translate_book(ctx: &PreprocessorContext, mut book: Book) -> anyhow::Result<B
     let po_file = cfg
         .get("po-file")
         .ok_or_else(|| anyhow!("Missing preprocessor.gettext.po-file config value"))?;
-    let path = po_file.as_str().ok_or_else(|| {
-        anyhow!(
-            "Expected a string for preprocessor.gettext.po-file, found {po_file} ({})",
-            po_file.type_str()
-        )
-    })?;
-    let catalog = po_file::parse(Path::new(path))
-        .map_err(|err| anyhow!("{err}"))
-        .with_context(|| format!("Could not parse {path} as PO file"))?;
+    let catalogs = po_file_paths(po_file)?
+        .into_iter()
+        .map(|path| {
+            po_file::parse(Path::new(path))
+                .map_err(|err| anyhow!("{err}"))
+                .with_context(|| format!("Could not parse {path} as PO file"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let strict = cfg
+        .get("strict")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
 
+    let mut stats = TranslationStats::default();
     book.for_each_mut(|item| match item {
         BookItem::Chapter(ch) => {
-            ch.content = translate(&ch.content, &catalog);
-            ch.name = translate(&ch.name, &catalog);
+            let chapter_ctxt = ch
+                .path
+                .as_deref()
+                .map(|path| path.to_string_lossy().into_owned());
+            ch.content = translate(&ch.content, &catalogs, chapter_ctxt.as_deref(), &mut stats);
+            ch.name = translate(&ch.name, &catalogs, chapter_ctxt.as_deref(), &mut stats);
         }
         BookItem::Separator => {}
         BookItem::PartTitle(title) => {
-            *title = translate(title, &catalog);
+            *title = translate(title, &catalogs, None, &mut stats);
         }
     });
+    stats.report();
+
+    if strict && stats.untranslated > 0 {
+        return Err(anyhow!(
+            "preprocessor.gettext.strict is set and {} of {} messages are untranslated",
+            stats.untranslated,
+            stats.total,
+        ));
+    }
 
     Ok(book)
 }
 
+/// Checks whether `preprocessor.gettext.renderer` in `book.toml` allows
+/// us to run against `renderer`. Mirrors the way a preprocessor can
+/// restrict itself to specific backends: an absent `renderer` list (or
+/// an absent `book.toml`, when `supports` is queried from an unusual
+/// working directory) means "support everything", matching today's
+/// default.
+fn renderer_is_supported(renderer: &str) -> anyhow::Result<bool> {
+    let Ok(cfg) = Config::from_disk("book.toml") else {
+        return Ok(true);
+    };
+    let Some(gettext_cfg) = cfg.get_preprocessor("gettext") else {
+        return Ok(true);
+    };
+    let Some(renderers) = gettext_cfg.get("renderer") else {
+        return Ok(true);
+    };
+    let renderers = renderers.as_array().ok_or_else(|| {
+        anyhow!(
+            "Expected an array for preprocessor.gettext.renderer, found {renderers} ({})",
+            renderers.type_str()
+        )
+    })?;
+
+    Ok(renderers
+        .iter()
+        .filter_map(|value| value.as_str())
+        .any(|supported| supported == renderer))
+}
+
 fn preprocess() -> anyhow::Result<()> {
     let (ctx, book) = CmdPreprocessor::parse_input(io::stdin())?;
     let book_version = Version::parse(&ctx.mdbook_version)?;
@@ -118,8 +164,14 @@ fn preprocess() -> anyhow::Result<()> {
 fn main() -> anyhow::Result<()> {
     if std::env::args().len() == 3 {
         assert_eq!(std::env::args().nth(1).as_deref(), Some("supports"));
-        // Signal that we support all renderers.
-        process::exit(0);
+        let renderer = std::env::args()
+            .nth(2)
+            .expect("mdbook always passes a renderer to query");
+        if renderer_is_supported(&renderer)? {
+            process::exit(0);
+        } else {
+            process::exit(1);
+        }
     }
 
     preprocess()
@@ -0,0 +1,212 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Core translation logic shared by `mdbook-gettext` and its fuzz
+//! targets.
+
+use polib::catalog::Catalog;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use pulldown_cmark_to_cmark::cmark;
+
+/// Markdown extensions our chapters are allowed to use. This must match
+/// what `mdbook` itself enables so that the event stream we parse here
+/// round-trips through `pulldown-cmark-to-cmark` unchanged.
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
+/// Block-level tags which delimit translatable text. We flush whatever
+/// inline content we've accumulated whenever we cross one of these so
+/// that, e.g., a whole paragraph (not a whole chapter) becomes one
+/// translatable message.
+fn is_block_tag(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph
+            | Tag::Heading(..)
+            | Tag::BlockQuote
+            | Tag::CodeBlock(_)
+            | Tag::List(_)
+            | Tag::Item
+            | Tag::FootnoteDefinition(_)
+            | Tag::Table(_)
+            | Tag::TableHead
+            | Tag::TableRow
+            | Tag::TableCell
+    )
+}
+
+/// Tallies how many translatable messages `translate` encountered and
+/// how many of those had no usable translation, so callers can report
+/// build-time translation coverage.
+#[derive(Default)]
+pub struct TranslationStats {
+    pub total: usize,
+    pub untranslated: usize,
+}
+
+impl TranslationStats {
+    fn record(&mut self, was_translated: bool) {
+        self.total += 1;
+        if !was_translated {
+            self.untranslated += 1;
+        }
+    }
+
+    pub fn translated(&self) -> usize {
+        self.total - self.untranslated
+    }
+
+    pub fn percent_complete(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            100.0 * self.translated() as f64 / self.total as f64
+        }
+    }
+
+    pub fn report(&self) {
+        eprintln!(
+            "gettext: {}/{} messages translated ({:.1}% complete, {} untranslated)",
+            self.translated(),
+            self.total,
+            self.percent_complete(),
+            self.untranslated,
+        );
+    }
+}
+
+/// Looks up `msgid` in `catalogs`, in order, returning the first
+/// non-empty translation found. This lets a book-specific override
+/// catalog be layered on top of a shared base translation by listing
+/// it first. When `ctxt` is given, a `msgctxt`-qualified match in a
+/// catalog is preferred over that catalog's context-less one, so two
+/// identical source strings (e.g. a bare "Example" heading) can carry
+/// different translations depending on where they appear.
+fn find_translation<'a>(
+    catalogs: &'a [Catalog],
+    msgid: &str,
+    ctxt: Option<&str>,
+) -> Option<&'a str> {
+    catalogs.iter().find_map(|catalog| {
+        let message = ctxt
+            .and_then(|ctxt| catalog.find_message_by_context(msgid, ctxt))
+            .or_else(|| catalog.find_message(msgid))?;
+        message
+            .get_msgstr()
+            .ok()
+            .filter(|msgstr| !msgstr.is_empty())
+            .map(|msgstr| msgstr.as_str())
+    })
+}
+
+/// Looks up `group`'s reconstructed markdown in `catalogs` and appends
+/// either the translated events or the original ones to `events`.
+/// Returns the group's reconstructed msgid, if any, so callers can
+/// derive context for later groups (e.g. the nearest preceding
+/// heading) from it.
+fn flush_group<'a>(
+    group: &mut Vec<Event<'a>>,
+    events: &mut Vec<Event<'a>>,
+    catalogs: &'a [Catalog],
+    ctxt: Option<&str>,
+    stats: &mut TranslationStats,
+) -> Option<String> {
+    if group.is_empty() {
+        return None;
+    }
+
+    let mut msgid = String::new();
+    cmark(group.iter(), &mut msgid).expect("formatting a Vec<Event> to a String cannot fail");
+
+    let translated = find_translation(catalogs, &msgid, ctxt);
+    stats.record(translated.is_some());
+    match translated {
+        Some(msgstr) => events.extend(Parser::new_ext(msgstr, markdown_options())),
+        None => events.append(group),
+    }
+    group.clear();
+    Some(msgid)
+}
+
+/// Translates the inline prose in `text`, preferring translations
+/// scoped to `chapter_ctxt` (typically the chapter's path) and, within
+/// a chapter, to the nearest preceding heading.
+pub fn translate<'a>(
+    text: &'a str,
+    catalogs: &'a [Catalog],
+    chapter_ctxt: Option<&str>,
+    stats: &mut TranslationStats,
+) -> String {
+    // Blank-line-only input has no prose to translate and is returned
+    // exactly as given.
+    if text.chars().all(|c| c == '\n') {
+        return text.to_string();
+    }
+
+    let mut events = Vec::new();
+    let mut group = Vec::new();
+    let mut in_code_block = false;
+    let mut ctxt = chapter_ctxt.map(str::to_string);
+
+    for event in Parser::new_ext(text, markdown_options()) {
+        match &event {
+            Event::Start(tag) if is_block_tag(tag) => {
+                flush_group(&mut group, &mut events, catalogs, ctxt.as_deref(), stats);
+                in_code_block |= matches!(tag, Tag::CodeBlock(_));
+                events.push(event);
+            }
+            Event::End(tag) if is_block_tag(tag) => {
+                let is_heading = matches!(tag, Tag::Heading(..));
+                let msgid = flush_group(&mut group, &mut events, catalogs, ctxt.as_deref(), stats);
+                in_code_block &= !matches!(tag, Tag::CodeBlock(_));
+                if is_heading {
+                    if let Some(msgid) = msgid {
+                        ctxt = Some(msgid);
+                    }
+                }
+                events.push(event);
+            }
+            // Rule and FootnoteReference are kept out of messages and
+            // flush like the block tags above, so a footnote reference
+            // splits the prose around it into separate messages.
+            // Event::Code and Event::Html, by
+            // contrast, accumulate into the group below: an inline code
+            // span or raw HTML tag is part of the sentence around it,
+            // and splitting there would fragment "Call `foo()` now."
+            // into two untranslatable fragments instead of one msgid.
+            // Only block-level CodeBlock contents (handled by
+            // `in_code_block` above) are passthrough.
+            Event::Rule | Event::FootnoteReference(_) => {
+                flush_group(&mut group, &mut events, catalogs, ctxt.as_deref(), stats);
+                events.push(event);
+            }
+            _ if in_code_block => events.push(event),
+            _ => group.push(event),
+        }
+    }
+    flush_group(&mut group, &mut events, catalogs, ctxt.as_deref(), stats);
+
+    let mut output = String::with_capacity(text.len());
+    cmark(events.iter(), &mut output).expect("formatting a Vec<Event> to a String cannot fail");
+
+    if text.ends_with('\n') && !output.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output
+}